@@ -0,0 +1,94 @@
+//! Coordinate assignment stage: turns ranks plus within-rank order into actual `(x, y)`
+//! positions, including for the virtual nodes later stages route edges through.
+
+use crate::simplex::{self, SimplexEdge};
+use crate::{Gansner, NodeIx};
+
+impl<NodeData> Gansner<NodeData> {
+    /// Stage 3: assign every node (real and virtual) a position.
+    pub(crate) fn assign_coordinates(&mut self, order: &[Vec<NodeIx>]) {
+        self.assign_y(order);
+        self.assign_x(order);
+    }
+
+    /// `y` is simply the rank's cumulative offset: each rank's height is the tallest node in it,
+    /// and ranks are stacked with `rank_sep` between them.
+    fn assign_y(&mut self, order: &[Vec<NodeIx>]) {
+        let mut y = 0.0f64;
+        for rank in order {
+            let height = rank
+                .iter()
+                .map(|&n| self.graph[n].size.height)
+                .fold(0.0, f64::max);
+            let center = y + height / 2.0;
+            for &n in rank {
+                self.graph[n].position.y = center;
+            }
+            y += height + self.rank_sep;
+        }
+    }
+
+    /// `x` comes from Gansner's auxiliary-graph network-simplex method: build a helper graph
+    /// with one extra node per edge, connect it to that edge's two endpoints with Ω-weighted
+    /// edges that penalize horizontal deviation between them, and add zero-weight separation
+    /// edges between consecutive nodes in a rank so the order chosen earlier is preserved. The
+    /// minimal-cost solution is the left-aligned, balanced, minimal-bend `x` assignment.
+    fn assign_x(&mut self, order: &[Vec<NodeIx>]) {
+        let real_node_count = self.graph.node_count();
+        let edge_ixs: Vec<_> = self.graph.edge_indices().collect();
+
+        let mut aux_edges = Vec::with_capacity(edge_ixs.len() * 2);
+        for (offset, &edge_ix) in edge_ixs.iter().enumerate() {
+            let (tail, head) = self.graph.edge_endpoints(edge_ix).unwrap();
+            let aux_node = real_node_count + offset;
+            let omega = segment_weight(
+                self.graph[tail].ix.is_none(),
+                self.graph[head].ix.is_none(),
+            );
+            let weight = omega * self.graph[edge_ix].weight;
+            aux_edges.push(SimplexEdge {
+                tail: aux_node,
+                head: tail.index(),
+                min_len: 0,
+                weight,
+            });
+            aux_edges.push(SimplexEdge {
+                tail: aux_node,
+                head: head.index(),
+                min_len: 0,
+                weight,
+            });
+        }
+
+        for rank in order {
+            for pair in rank.windows(2) {
+                let (a, b) = (pair[0], pair[1]);
+                let sep = (self.graph[a].size.width + self.graph[b].size.width) / 2.0
+                    + self.node_sep;
+                aux_edges.push(SimplexEdge {
+                    tail: a.index(),
+                    head: b.index(),
+                    min_len: sep.ceil() as i64,
+                    weight: 0.0,
+                });
+            }
+        }
+
+        let node_count = real_node_count + edge_ixs.len();
+        let positions = simplex::solve(node_count, &aux_edges);
+        for n in self.graph.node_indices() {
+            self.graph[n].position.x = positions[n.index()] as f64;
+        }
+    }
+}
+
+/// Gansner's Ω weight for a segment, by how many of its endpoints are virtual: straightening a
+/// segment between two virtual nodes (the middle of a long edge) matters most, then a segment
+/// touching one virtual node, then an edge between two real nodes.
+fn segment_weight(tail_virtual: bool, head_virtual: bool) -> f64 {
+    match (tail_virtual, head_virtual) {
+        (true, true) => 8.0,
+        (false, false) => 1.0,
+        _ => 2.0,
+    }
+}
@@ -1,7 +1,9 @@
 //! module with the layout algorithm.
 
-use crate::{Edge, EdgeIx, Gansner, NodeIx};
-use petgraph::{visit::EdgeRef, Direction::*};
+use crate::simplex::{self, SimplexEdge};
+use crate::{Edge, EdgeIx, Gansner, NodeIx, NodeWeight, RankIdx};
+use petgraph::{algo::TarjanScc, visit::EdgeRef, Direction::*};
+use std::collections::{HashMap, HashSet};
 
 impl<NodeData> Gansner<NodeData> {
     pub(crate) fn layout_impl(&mut self, debug: bool) {
@@ -15,7 +17,7 @@ impl<NodeData> Gansner<NodeData> {
         #[cfg(debug_assertions)]
         let copy = self.graph.map(|_, _| (), |_, _| ());
 
-        let modified = self.make_acyclic();
+        let mut modified = self.make_acyclic();
         debug_assert!(!petgraph::algo::is_cyclic_directed(&self.graph));
         debug_assert!(self.rank_hints.rank_min().all(|node_idx| self
             .graph
@@ -28,7 +30,11 @@ impl<NodeData> Gansner<NodeData> {
             .next()
             .is_none()));
 
-        // TODO assign ranks
+        self.assign_ranks(debug);
+        self.insert_virtual_nodes(&mut modified);
+        let order = self.order_ranks(debug);
+        self.assign_coordinates(&order);
+        self.route_edges(&mut modified);
 
         self.undo_modify_edges(modified);
         #[cfg(debug_assertions)]
@@ -40,110 +46,448 @@ impl<NodeData> Gansner<NodeData> {
     // Hmm actually I don't think I can mutate the graph and re-create the original. Instead, we
     // could mark
     /// This function
-    ///  1. condenses all nodes in each user-supplied sets (inc. min and max) down into a single
-    ///     node.
-    ///  2. ensures that all edges are outgoing for Smin and incoming for Smax.
+    ///  1. ensures that all edges are outgoing for Smin and incoming for Smax.
     ///  2. removes loops.
     ///  3. merges multiple edges into a single edge whose weight is the sum of the individual
     ///     edges' weight.
     ///  4. removes leaf nodes that are not part of S1..Sk, Smin, Smax.
-    ///  5. makes the graph acyclic by reversing edges. This is currently done using the greedy
-    ///     algorithm, but we probably want to switch to gansner's heuristic algo.
+    ///  5. makes the graph acyclic by reversing edges, using Gansner's DFS/SCC heuristic.
     ///  6. adds an edge for all nodes with no incoming edge from Smin with min rank length = 0,
     ///     and the same for nodes with no outgoing edge (min rank length 0 edge to Smax).
     ///
+    /// Note: S1..Sk (the `set_rank_same` groups) are *not* condensed here - they're aliased down
+    /// to a single representative node's index later, in `assign_ranks`, once ranks are what's
+    /// being computed. Items 3, 4 and 6 above remain aspirational (see the TODO on item 6, below);
+    /// nothing condenses multi-edges or prunes S1..Sk/Smin/Smax leaves yet either.
+    ///
     /// The return value is the information required to reconstruct the original graph.
-    fn prepare_rank_assignment(&mut self) -> RankAssignmentAdjustment {
-        let mut remove_ixs = Vec::new();
-        let mut reverse_ixs = Vec::new();
-
+    fn make_acyclic(&mut self) -> RankAssignmentAdjustment {
         // Ensure all edges go out of min rank and into max rank
         //
         // If the edge is Smin -> Smin or Smax -> Smax then we reverse it, but it will have no
         // effect on rank assignment, since both nodes will be given min/max rank.
+        let mut to_reverse: HashSet<EdgeIx> = HashSet::new();
         for node_id in self.rank_hints.rank_min() {
             for edge in self.graph.edges_directed(node_id.0, Incoming) {
-                reverse_ixs.push(edge.id());
+                to_reverse.insert(edge.id());
             }
         }
-
         for node_id in self.rank_hints.rank_max() {
             for edge in self.graph.edges_directed(node_id.0, Outgoing) {
-                reverse_ixs.push(edge.id());
+                to_reverse.insert(edge.id());
             }
         }
 
-        let mut reverse: Vec<_> = reverse_ixs
-            .drain(..)
-            .map(|id| self.reverse_edge(id))
+        // Self loops can't be broken by reversal, so they're pulled out before the cycle-breaking
+        // heuristic below, which only has to deal with cycles of length > 1.
+        //
+        // We don't actually need to remove edges, but for now we do because it means we can check
+        // the output of this stage is a DAG.
+        let to_remove: HashSet<EdgeIx> = self
+            .graph
+            .edge_references()
+            .filter(|edge| edge.source() == edge.target())
+            .map(|edge| edge.id())
             .collect();
 
-        // Make acyclic
-        //
-        // TODO assuming the definition of a feedback set (FS) from rtamassi handbook, I need to
-        // make sure that this algorithm (which returns a feedback arc set (FAS)) also returns a
-        // feedback set.
+        let (remap, remove) = self.rebuild_edges(&to_remove, &to_reverse);
+        let mut reverse: Vec<EdgeIx> = to_reverse.iter().map(|id| remap[id]).collect();
+
+        // Make acyclic: Gansner's heuristic, rather than the generic `greedy_feedback_arc_set`
+        // this replaced.
         //
-        // If we want to implement Gansner's algo (we do) the method is:
         //  1. Create a `TarjanScc`
         //  2. repeat
         //      i. `run` it on our graph
-        //      ii. for each component, do a DFS and reverse the edge that
-        //          participates in the most cycles
+        //      ii. for each non-trivial component, do a DFS and reverse every edge that closes a
+        //          cycle (i.e. runs to a node still on the DFS stack)
         //
         //     until there are no non-trivial strongly connected components
         //
-        // This is however work that I'm avoiding for now. The scc methods in petgraph claim that
-        // the node order is arbitary, but it looks like it is order of insertion, which is what we
-        // want.
-        for edge in petgraph::algo::greedy_feedback_arc_set(&self.graph) {
-            // skip loops
-            if edge.source() == edge.target() {
-                remove_ixs.push(edge.id());
-            } else {
-                reverse_ixs.push(edge.id());
+        // The back edges of one DFS over a component are exactly a feedback arc set for it, and
+        // reversing edges *within* a component can't create a cycle that spans components (the
+        // condensation into SCCs is already acyclic, and we never touch the edges between
+        // components) - so in practice this `loop` always reverses every component's back edges
+        // on its first pass, then makes one more `TarjanScc` pass that confirms none remain. It's
+        // still written as a loop rather than a single pass, since re-deriving SCCs from the
+        // now-modified graph is a cheap, self-checking way to confirm the result is acyclic rather
+        // than trusting that invariant blindly.
+        //
+        // Every component's back edges (and in fact every component found in the same `TarjanScc`
+        // pass) are reversed together through `rebuild_edges`, rather than one at a time: reversing
+        // one edge via `remove_edge`+`add_edge` swap-removes, which would otherwise silently
+        // reindex any other back edge from this (or an earlier) component still waiting to be
+        // reversed, and any entry already collected in `reverse`.
+        loop {
+            let mut sccs = Vec::new();
+            TarjanScc::new().run(&self.graph, |scc| {
+                if scc.len() > 1 {
+                    sccs.push(scc.to_vec());
+                }
+            });
+            if sccs.is_empty() {
+                break;
+            }
+            let mut back_edges: HashSet<EdgeIx> = HashSet::new();
+            for scc in sccs {
+                let component: HashSet<NodeIx> = scc.into_iter().collect();
+                back_edges.extend(self.find_back_edges(&component));
             }
+            let (remap, removed) = self.rebuild_edges(&HashSet::new(), &back_edges);
+            debug_assert!(removed.is_empty());
+            reverse = reverse.into_iter().map(|id| remap[&id]).collect();
+            reverse.extend(back_edges.iter().map(|id| remap[id]));
         }
 
-        // We don't actually need to remove edges, but for now we do because it means we can check
-        // the output of this stage is a DAG.
-        let remove = remove_ixs
-            .drain(..)
-            .map(|id| {
-                let (from, _) = self.graph.edge_endpoints(id).unwrap();
-                let weight = self.graph.remove_edge(id).unwrap();
-                RemovedEdge {
-                    from,
-                    to: from,
-                    weight,
-                }
-            })
-            .collect();
-
-        reverse.extend(reverse_ixs.into_iter().map(|id| self.reverse_edge(id)));
-
         // TODO in the paper it talks about adding temp edges from Smin to e and from e to Smax
         // when there is no incoming/outgoing edge respectively, to ensure all nodes lie on a path
         // from Smin to Smax. I'm not bothering to do this for now - will add it if/when I
         // understand why it is needed, so I know what to choose for the weight/nodes.
 
-        (remove, reverse)
+        RankAssignmentAdjustment {
+            remove,
+            reverse,
+            virtual_chains: Vec::new(),
+        }
+    }
+
+    /// Remove every edge in `to_remove` and reverse every edge in `to_reverse` in one pass,
+    /// rather than one at a time: petgraph's `remove_edge` swap-removes (moving whatever edge
+    /// currently has the highest index into the freed slot), so repeated in-place removal or
+    /// reversal would silently reindex any other edge in either set still waiting to be
+    /// processed. This snapshots every edge, clears the edge list, and rebuilds it, returning a
+    /// remap from every surviving edge's old index to its new one plus the edges pulled out by
+    /// `to_remove`, in encounter order. Same technique as `insert_virtual_nodes` uses for its own
+    /// bulk edge rewrite.
+    fn rebuild_edges(
+        &mut self,
+        to_remove: &HashSet<EdgeIx>,
+        to_reverse: &HashSet<EdgeIx>,
+    ) -> (HashMap<EdgeIx, EdgeIx>, Vec<RemovedEdge<Edge>>) {
+        let snapshot: Vec<(EdgeIx, NodeIx, NodeIx, Edge)> = self
+            .graph
+            .edge_references()
+            .map(|edge| (edge.id(), edge.source(), edge.target(), edge.weight().clone()))
+            .collect();
+        self.graph.clear_edges();
+
+        let mut remap = HashMap::with_capacity(snapshot.len());
+        let mut removed = Vec::new();
+        for (old_id, tail, head, weight) in snapshot {
+            if to_remove.contains(&old_id) {
+                removed.push(RemovedEdge {
+                    from: tail,
+                    to: tail,
+                    weight,
+                });
+                continue;
+            }
+            let new_id = if to_reverse.contains(&old_id) {
+                self.graph.add_edge(head, tail, weight)
+            } else {
+                self.graph.add_edge(tail, head, weight)
+            };
+            remap.insert(old_id, new_id);
+        }
+        (remap, removed)
+    }
+
+    /// DFS `component` (a single non-trivial strongly connected component) from each of its nodes
+    /// in turn, colouring nodes white/grey/black in the usual way, and return every edge found
+    /// running from a grey node to another grey node - i.e. every edge that runs back to an
+    /// ancestor still open on the DFS stack. These back edges are exactly a feedback arc set for
+    /// the component: removing them from the DFS tree's perspective leaves no path from a node
+    /// back to itself, so reversing them (see caller) makes the component acyclic.
+    fn find_back_edges(&self, component: &HashSet<NodeIx>) -> Vec<EdgeIx> {
+        let adj: HashMap<NodeIx, Vec<(NodeIx, EdgeIx)>> = component
+            .iter()
+            .map(|&n| {
+                let out = self
+                    .graph
+                    .edges_directed(n, Outgoing)
+                    .filter(|edge| component.contains(&edge.target()))
+                    .map(|edge| (edge.target(), edge.id()))
+                    .collect();
+                (n, out)
+            })
+            .collect();
+
+        // 0 = white (unvisited), 1 = grey (on the current DFS stack), 2 = black (finished).
+        let mut color: HashMap<NodeIx, u8> = component.iter().map(|&n| (n, 0)).collect();
+        let mut back_edges = Vec::new();
+
+        let mut starts: Vec<NodeIx> = component.iter().copied().collect();
+        starts.sort_by_key(|n| n.index());
+        for start in starts {
+            if color[&start] != 0 {
+                continue;
+            }
+            color.insert(start, 1);
+            let mut stack: Vec<(NodeIx, usize)> = vec![(start, 0)];
+            while let Some(&mut (node, ref mut next_child)) = stack.last_mut() {
+                let children = &adj[&node];
+                if *next_child < children.len() {
+                    let (target, edge_id) = children[*next_child];
+                    *next_child += 1;
+                    match color[&target] {
+                        0 => {
+                            color.insert(target, 1);
+                            stack.push((target, 0));
+                        }
+                        1 => back_edges.push(edge_id),
+                        _ => {}
+                    }
+                } else {
+                    color.insert(node, 2);
+                    stack.pop();
+                }
+            }
+        }
+        back_edges
     }
 
     /// flip any reversed edges
-    fn undo_modify_edges(&mut self, (remove, reverse): (Vec<RemovedEdge<Edge>>, Vec<EdgeIx>)) {
-        for RemovedEdge { from, to, weight } in remove {
+    fn undo_modify_edges(&mut self, adjustment: RankAssignmentAdjustment) {
+        // Undo the simple (non-virtualized) reversals first, in one batched pass via
+        // `rebuild_edges`, while the graph still looks exactly as `route_edges` left it. Removing
+        // the virtual-chain nodes below drops their incident edges as a side effect of petgraph's
+        // `remove_node` - and like `remove_edge`, that's a swap-remove, which would silently
+        // reindex any edge still referenced by `adjustment.reverse` if done first.
+        let to_reverse: HashSet<EdgeIx> = adjustment.reverse.into_iter().collect();
+        self.rebuild_edges(&HashSet::new(), &to_reverse);
+
+        for RemovedEdge { from, to, mut weight } in adjustment.remove {
+            // `remove` only ever holds self loops (see `make_acyclic`), which never pass through
+            // `route_edges` since they don't exist in the graph again until here - route them
+            // directly now that `from`'s position is known, rather than leaving `position` at its
+            // default empty path.
+            weight.position = crate::spline::route_self_loop(self.graph[from].position, self.graph[from].size);
             self.graph.add_edge(from, to, weight);
         }
-        for id in reverse {
-            self.reverse_edge(id);
+
+        // Collapse virtual-node chains back into the single long edge they replaced. We remove
+        // every chain node in descending `NodeIndex` order so that petgraph's swap-remove (which
+        // moves the last node into the freed slot) always swaps a node with itself, leaving every
+        // other node index untouched; nothing above still holds an `EdgeIx` for any edge these
+        // nodes carry, so the edges they take with them don't need the same treatment.
+        let mut virtual_nodes: Vec<NodeIx> = adjustment
+            .virtual_chains
+            .iter()
+            .flat_map(|chain| chain.nodes.iter().copied())
+            .collect();
+        virtual_nodes.sort_by_key(|node| std::cmp::Reverse(node.index()));
+        for node in virtual_nodes {
+            self.graph.remove_node(node);
+        }
+        for VirtualChain {
+            from,
+            to,
+            edge,
+            reversed,
+            ..
+        } in adjustment.virtual_chains
+        {
+            let edge_ix = self.graph.add_edge(from, to, edge);
+            // This edge was *also* reversed for cycle-breaking before it got virtualized, so
+            // `adjustment.reverse` couldn't carry a still-valid index for it (see
+            // `insert_virtual_nodes`) - flip it back here instead, now that it exists again as a
+            // single edge. Each chain's edge is created and (if needed) reversed within the same
+            // iteration, using the index just handed back by `add_edge`, so this isn't exposed to
+            // the same swap-remove hazard as a list of indices collected across iterations.
+            if reversed {
+                self.reverse_edge(edge_ix);
+            }
+        }
+    }
+
+    /// Stage 1b: break every edge whose rank span exceeds 1 into a chain of unit-length edges
+    /// through virtual nodes (one per intermediate rank), so ordering and coordinate assignment
+    /// only ever have to deal with adjacent-rank edges. Each chain is recorded on `modified` so
+    /// `undo_modify_edges` can collapse it back into the original edge once later stages are
+    /// done reading the virtual nodes' positions.
+    fn insert_virtual_nodes(&mut self, modified: &mut RankAssignmentAdjustment) {
+        // Snapshot every edge up front, then wipe and rebuild the edge list from that snapshot,
+        // rather than removing edges one at a time as we find ones to virtualize. petgraph's
+        // `remove_edge` swap-removes (moving whatever edge currently has the highest index into
+        // the freed slot), so repeated in-place removal would silently reindex other edges -
+        // including the ones `modified.reverse` points at. Rebuilding lets us re-derive every
+        // surviving edge's new index in one pass instead.
+        let snapshot: Vec<(EdgeIx, NodeIx, NodeIx, Edge)> = self
+            .graph
+            .edge_references()
+            .map(|edge| (edge.id(), edge.source(), edge.target(), edge.weight().clone()))
+            .collect();
+        // Edges already reversed for cycle-breaking, recorded before we start handing out new
+        // indices below - an edge that's *both* reversed and virtualized can't be represented by
+        // `modified.reverse` any more (there's no single edge left at its old index to flip), so
+        // that reversal has to travel with its `VirtualChain` instead; see below.
+        let reversed: HashSet<EdgeIx> = modified.reverse.iter().copied().collect();
+        self.graph.clear_edges();
+
+        let mut remap: HashMap<EdgeIx, EdgeIx> = HashMap::with_capacity(snapshot.len());
+        for (old_ix, tail, head, edge) in snapshot {
+            let tail_rank = self.graph[tail].rank;
+            let head_rank = self.graph[head].rank;
+            if head_rank <= tail_rank + 1 {
+                let new_ix = self.graph.add_edge(tail, head, edge);
+                remap.insert(old_ix, new_ix);
+                continue;
+            }
+
+            let mut chain_nodes = Vec::with_capacity(head_rank - tail_rank - 1);
+            let mut prev = tail;
+            for rank in (tail_rank + 1)..head_rank {
+                let virtual_node = self.graph.add_node(NodeWeight::new_virtual(rank));
+                self.graph
+                    .add_edge(prev, virtual_node, Edge::new().with_weight(edge.weight));
+                chain_nodes.push(virtual_node);
+                prev = virtual_node;
+            }
+            self.graph
+                .add_edge(prev, head, Edge::new().with_weight(edge.weight));
+
+            modified.virtual_chains.push(VirtualChain {
+                from: tail,
+                to: head,
+                edge,
+                nodes: chain_nodes,
+                reversed: reversed.contains(&old_ix),
+            });
+            // No remap entry: this edge no longer exists as a single edge (it's been replaced by
+            // the chain above), so `modified.reverse` can't carry a usable index for it - the
+            // `reversed` flag above is how that gets handled by `undo_modify_edges` instead.
+        }
+
+        modified
+            .reverse
+            .retain_mut(|id| match remap.get(id) {
+                Some(&new_id) => {
+                    *id = new_id;
+                    true
+                }
+                None => false,
+            });
+    }
+
+    /// Stage 1: assign every node an integer `rank` via Gansner's network-simplex method,
+    /// minimizing Σ weight·(rank(head)−rank(tail)) subject to rank(head)−rank(tail) ≥
+    /// `Edge::min_rank_len`. The graph is acyclic by this point (see `make_acyclic`), which is
+    /// what the simplex solver's initial longest-path ranking requires.
+    ///
+    /// Every `set_rank_same` group is aliased down to one representative node's index before
+    /// ranking, so the simplex solver only ever sees that one index for the whole group and every
+    /// member ends up with the representative's rank. Smin/Smax are not aliased this way; their
+    /// only handling is the edge orientation done in `make_acyclic`.
+    fn assign_ranks(&mut self, debug: bool) {
+        let node_count = self.graph.node_count();
+
+        let mut representative: Vec<usize> = (0..node_count).collect();
+        for group in self.rank_hints.same_rank_groups() {
+            let mut members = group.into_iter().map(|node| node.0.index());
+            if let Some(leader) = members.next() {
+                for member in members {
+                    representative[member] = leader;
+                }
+            }
+        }
+
+        // An edge between two nodes in the same `set_rank_same` group aliases down to a self-loop
+        // here, once both ends are replaced by their shared representative - the group already
+        // forces them to the same rank, so the edge adds nothing, and handing it to the simplex
+        // solver as a `min_len >= 1` constraint on a zero rank difference would violate the
+        // acyclic precondition `simplex::rank` relies on.
+        let edges: Vec<SimplexEdge> = self
+            .graph
+            .edge_references()
+            .map(|edge| SimplexEdge {
+                tail: representative[edge.source().index()],
+                head: representative[edge.target().index()],
+                min_len: edge.weight().min_rank_len as i64,
+                weight: edge.weight().weight,
+            })
+            .filter(|edge| edge.tail != edge.head)
+            .collect();
+
+        let ranks = simplex::rank(node_count, &edges);
+        let max_rank = ranks.iter().copied().max().unwrap_or(0);
+        for (node_ix, node) in self.graph.node_weights_mut().enumerate() {
+            node.rank = ranks[representative[node_ix]] as RankIdx;
+        }
+
+        if debug {
+            println!("rank: {node_count} nodes placed across {} ranks", max_rank + 1);
+        }
+    }
+
+    /// Stage 4: now every node (real and virtual) has a position, route each edge as a spline
+    /// through the waypoints its chain of virtual nodes provides. Multi-rank edges are routed
+    /// here, while their virtual nodes still exist, and the resulting path is stashed on the
+    /// `VirtualChain`'s `edge` so it survives into `undo_modify_edges`'s collapse back to a single
+    /// edge. Single-rank edges are routed directly.
+    ///
+    /// Every edge is routed here in the *current* (possibly cycle-break-reversed) direction, but
+    /// `undo_modify_edges` hasn't run yet, so an edge that's due to be flipped back would otherwise
+    /// end up with a path that runs backwards relative to the source/target it's restored to. We
+    /// route those edges tail-to-head as seen right now and then reverse the point order, so the
+    /// stored path already matches the direction the edge will have once it's un-reversed.
+    fn route_edges(&mut self, modified: &mut RankAssignmentAdjustment) {
+        for chain in &mut modified.virtual_chains {
+            let mut points = Vec::with_capacity(chain.nodes.len() + 2);
+            points.push(self.graph[chain.from].position);
+            points.extend(chain.nodes.iter().map(|&n| self.graph[n].position));
+            points.push(self.graph[chain.to].position);
+            let (from_size, to_size) = (self.graph[chain.from].size, self.graph[chain.to].size);
+            chain.edge.position = if chain.reversed {
+                points.reverse();
+                crate::spline::route(points, to_size, from_size)
+            } else {
+                crate::spline::route(points, from_size, to_size)
+            };
+        }
+
+        let reversed: HashSet<EdgeIx> = modified.reverse.iter().copied().collect();
+        for edge_ix in self.graph.edge_indices().collect::<Vec<_>>() {
+            let (tail, head) = self.graph.edge_endpoints(edge_ix).unwrap();
+            if self.graph[tail].ix.is_none() || self.graph[head].ix.is_none() {
+                // A segment of a not-yet-collapsed virtual chain; its path is carried on the
+                // chain's own `edge` above, not on this ephemeral segment edge.
+                continue;
+            }
+            let mut points = vec![self.graph[tail].position, self.graph[head].position];
+            let (mut from_size, mut to_size) = (self.graph[tail].size, self.graph[head].size);
+            if reversed.contains(&edge_ix) {
+                points.reverse();
+                std::mem::swap(&mut from_size, &mut to_size);
+            }
+            let path = crate::spline::route(points, from_size, to_size);
+            self.graph[edge_ix].position = path;
         }
     }
 }
 
 struct RankAssignmentAdjustment {
-    /// edges that were removed
-    edges_removed: Vec<RemovedEdge>,
+    /// edges that were removed (self loops) while breaking cycles; re-added verbatim when the
+    /// original graph is restored
+    remove: Vec<RemovedEdge<Edge>>,
+    /// indices of edges that were reversed while breaking cycles; flipped back when the original
+    /// graph is restored
+    reverse: Vec<EdgeIx>,
+    /// virtual-node chains inserted for edges spanning more than one rank, recorded so they can
+    /// be collapsed back into the single edge they replaced
+    virtual_chains: Vec<VirtualChain>,
+}
+
+/// A virtual-node chain inserted in place of a single edge whose rank span exceeded 1, so it can
+/// be collapsed back into that edge once later stages are done reading the chain's positions.
+struct VirtualChain {
+    from: NodeIx,
+    to: NodeIx,
+    edge: Edge,
+    /// The virtual nodes making up the chain, in order from `from` to `to`.
+    nodes: Vec<NodeIx>,
+    /// Whether the edge this chain replaced had already been reversed for cycle-breaking, and so
+    /// needs reversing back again once `undo_modify_edges` collapses the chain into a single edge.
+    reversed: bool,
 }
 
 struct RemovedEdge<T> {
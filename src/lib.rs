@@ -19,11 +19,16 @@
 //! [layered graph]: https://en.wikipedia.org/wiki/Layered_graph_drawing
 pub use crate::rank_set::RankIdx;
 use crate::rank_set::RankSets;
-use kurbo::{Point, Size};
+use kurbo::{BezPath, Point, Size};
 use petgraph::graph::Graph;
+use petgraph::visit::EdgeRef;
 
+mod coords;
 mod layout;
+mod order;
 mod rank_set;
+mod simplex;
+mod spline;
 
 type GansnerGraph<NodeData> = Graph<NodeWeight<NodeData>, Edge>;
 
@@ -32,10 +37,21 @@ pub struct Gansner<NodeData> {
     /// User-supplied hints that certain nodes should share the same rank.
     rank_hints: RankSets,
 
+    /// Minimum gap between ranks, in the same units as node `Size`.
+    rank_sep: f64,
+    /// Minimum gap between neighbouring nodes within a rank, in the same units as node `Size`.
+    node_sep: f64,
+
     /// Has the layout algorithm been run since the last node/edge was added?
     fresh: bool,
 }
 
+impl<NodeData> Default for Gansner<NodeData> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<NodeData> Gansner<NodeData> {
     pub fn new() -> Self {
         Self::from_graph(Graph::new())
@@ -49,10 +65,37 @@ impl<NodeData> Gansner<NodeData> {
         Self {
             graph,
             rank_hints: RankSets::new(),
+            rank_sep: 1.,
+            node_sep: 1.,
             fresh: false,
         }
     }
 
+    /// Set the minimum gap between ranks (the `y` direction). Defaults to `1.`.
+    pub fn with_rank_sep(mut self, rank_sep: f64) -> Self {
+        self.set_rank_sep(rank_sep);
+        self
+    }
+
+    pub fn set_rank_sep(&mut self, rank_sep: f64) {
+        assert!(rank_sep >= 0., "rank separation must be >= 0");
+        self.fresh = false;
+        self.rank_sep = rank_sep;
+    }
+
+    /// Set the minimum gap between neighbouring nodes in a rank (the `x` direction). Defaults to
+    /// `1.`.
+    pub fn with_node_sep(mut self, node_sep: f64) -> Self {
+        self.set_node_sep(node_sep);
+        self
+    }
+
+    pub fn set_node_sep(&mut self, node_sep: f64) {
+        assert!(node_sep >= 0., "node separation must be >= 0");
+        self.fresh = false;
+        self.node_sep = node_sep;
+    }
+
     /// Add a node to the graph.
     ///
     /// Note that here order matters! When breaking cycles, the direction of edges will be reversed
@@ -98,7 +141,7 @@ impl<NodeData> Gansner<NodeData> {
         self.rank_hints.set_rank_min(node)
     }
 
-    /// Set the rank hint for a particular node to min.
+    /// Hint that two nodes should end up on the same rank.
     pub fn set_rank_same(&mut self, a: Node, b: Node) {
         self.fresh = false;
         self.rank_hints.set_rank(a, b)
@@ -106,7 +149,7 @@ impl<NodeData> Gansner<NodeData> {
 
     /// Run the layout algorithm
     pub fn layout(&mut self) {
-        if self.fresh == true {
+        if self.fresh {
             return;
         }
         self.layout_impl(false);
@@ -115,7 +158,7 @@ impl<NodeData> Gansner<NodeData> {
 
     /// Run the layout algorithm, writing debug information to stdout.
     pub fn layout_debug(&mut self) {
-        if self.fresh == true {
+        if self.fresh {
             return;
         }
         self.layout_impl(true);
@@ -137,39 +180,70 @@ impl<NodeData: Clone> Gansner<NodeData> {
         }
         self.graph
             .node_weights()
-            .map(|node| (node.ix.clone(), node.position))
+            .filter_map(|node| Some((node.ix.clone()?, node.position)))
+    }
+
+    /// Iterate over every user-supplied edge, together with its routed path. Must be called after
+    /// `layout`/`layout_debug`, as with [`Self::iter_nodes`].
+    pub fn iter_edges(&self) -> impl Iterator<Item = (Node, Node, &BezPath)> + '_ {
+        if !self.fresh {
+            panic!("must call `layout` before iterating over edges");
+        }
+        self.graph
+            .edge_references()
+            .map(|edge| (Node(edge.source()), Node(edge.target()), &edge.weight().position))
     }
 }
 
 /// Called `NodeWeight` so we can use `Node` for returned handles.
 #[derive(Clone)]
 struct NodeWeight<Ix> {
-    /// The index that was supplied by the user when adding the node.
-    ix: Ix,
+    /// The index that was supplied by the user when adding the node, or `None` for a virtual
+    /// node inserted by `layout_impl` to break up an edge spanning more than one rank.
+    ix: Option<Ix>,
     /// The user-supplied size of the node's bounding box.
     size: Size,
     /// The calculated position of the node
     position: Point,
+    /// The rank assigned by the network-simplex ranker in stage 1 of `layout_impl`.
+    rank: RankIdx,
+    /// This node's position within its rank, assigned by the ordering stage.
+    order: usize,
 }
 
 impl<Ix> NodeWeight<Ix> {
     fn new(ix: Ix, size: Size) -> Self {
         Self {
-            ix,
+            ix: Some(ix),
             size,
             position: Point::ZERO,
+            rank: 0,
+            order: 0,
+        }
+    }
+
+    /// A virtual node inserted in place of a multi-rank edge segment: no user data, zero size.
+    fn new_virtual(rank: RankIdx) -> Self {
+        Self {
+            ix: None,
+            size: Size::ZERO,
+            position: Point::ZERO,
+            rank,
+            order: 0,
         }
     }
 }
 
+#[derive(Clone)]
 struct Edge {
     /// Minimum number of ranks between edges (δ in paper). Defaults to `1`.
     min_rank_len: RankIdx,
     /// The edge weight, which should be a non-negative rational number (ω in paper). Defaults to
     /// `1`.
     weight: f64,
-    /// Calculated path of edge when drawn TODO type
-    position: (),
+    /// The calculated path of the edge when drawn, routed through any virtual nodes inserted for
+    /// a multi-rank edge. Empty until `layout`/`layout_debug` has been run.
+    position: BezPath,
 }
 
 impl Edge {
@@ -177,7 +251,7 @@ impl Edge {
         Self {
             min_rank_len: 1,
             weight: 1.,
-            position: (),
+            position: BezPath::new(),
         }
     }
 
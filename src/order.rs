@@ -0,0 +1,313 @@
+//! Ordering (crossing-minimization) stage.
+//!
+//! By the time this runs every node has a `rank` and every edge spans at most one rank (see
+//! `layout::insert_virtual_nodes`), so the remaining freedom is how nodes are arranged
+//! left-to-right within each rank. We pick an arrangement that minimizes edge crossings between
+//! adjacent ranks using Gansner's weighted-median + transpose heuristic.
+
+use crate::{Gansner, NodeIx};
+use petgraph::visit::EdgeRef;
+use petgraph::Direction::{Incoming, Outgoing};
+use std::collections::HashMap;
+
+/// Number of wmedian/transpose sweeps to try before settling on the best order seen.
+const MAX_ITERATIONS: usize = 24;
+
+impl<NodeData> Gansner<NodeData> {
+    /// Stage 2: assign every rank a left-to-right node order, minimizing crossings between
+    /// adjacent ranks, and record each node's position in its rank on `NodeWeight::order`.
+    /// Returns one `Vec<NodeIx>` per rank for the coordinate-assignment stage to consume.
+    pub(crate) fn order_ranks(&mut self, debug: bool) -> Vec<Vec<NodeIx>> {
+        let mut order = self.initial_order();
+        let mut best = order.clone();
+        let mut best_crossings = self.total_crossings(&order);
+
+        for iteration in 0..MAX_ITERATIONS {
+            self.wmedian(&mut order, iteration % 2 == 0);
+            self.transpose(&mut order);
+            let crossings = self.total_crossings(&order);
+            if crossings < best_crossings {
+                best_crossings = crossings;
+                best = order.clone();
+            }
+        }
+
+        for rank in &best {
+            for (i, &n) in rank.iter().enumerate() {
+                self.graph[n].order = i;
+            }
+        }
+        if debug {
+            println!("order: minimized to {best_crossings} crossing(s)");
+        }
+        best
+    }
+
+    /// Initial order: a DFS from the min-rank nodes, appending each node to its own rank's order
+    /// as it's first reached.
+    fn initial_order(&self) -> Vec<Vec<NodeIx>> {
+        let rank_count = self
+            .graph
+            .node_weights()
+            .map(|node| node.rank)
+            .max()
+            .map_or(0, |max| max + 1);
+        let mut order = vec![Vec::new(); rank_count];
+        let mut visited = vec![false; self.graph.node_count()];
+
+        let mut roots: Vec<NodeIx> = self
+            .graph
+            .node_indices()
+            .filter(|&n| self.graph[n].rank == 0)
+            .collect();
+        roots.sort_by_key(|n| n.index());
+
+        // DFS with an explicit stack, pushed in reverse so children are visited left-to-right.
+        let mut stack = roots;
+        stack.reverse();
+        while let Some(n) = stack.pop() {
+            if visited[n.index()] {
+                continue;
+            }
+            visited[n.index()] = true;
+            order[self.graph[n].rank].push(n);
+            let mut children: Vec<NodeIx> = self
+                .graph
+                .edges_directed(n, Outgoing)
+                .map(|edge| edge.target())
+                .filter(|target| !visited[target.index()])
+                .collect();
+            children.reverse();
+            stack.extend(children);
+        }
+        // Any node not reachable from a min-rank root (a disconnected component) is appended in
+        // index order so every node still ends up somewhere.
+        for n in self.graph.node_indices() {
+            if !visited[n.index()] {
+                visited[n.index()] = true;
+                order[self.graph[n].rank].push(n);
+            }
+        }
+        order
+    }
+
+    /// Sweep all ranks, assigning each node the weighted median of its neighbours' positions in
+    /// the adjacent, already-fixed rank. `forward` sweeps top-to-bottom, using each node's
+    /// predecessors; sweeping bottom-to-top uses its successors instead.
+    fn wmedian(&self, order: &mut [Vec<NodeIx>], forward: bool) {
+        let rank_count = order.len();
+        let ranks: Vec<usize> = if forward {
+            (1..rank_count).collect()
+        } else {
+            (0..rank_count.saturating_sub(1)).rev().collect()
+        };
+        let (direction, adjacent_offset): (_, isize) = if forward {
+            (Incoming, -1)
+        } else {
+            (Outgoing, 1)
+        };
+
+        for r in ranks {
+            let adjacent_rank = (r as isize + adjacent_offset) as usize;
+            let position_in_adjacent: HashMap<NodeIx, usize> = order[adjacent_rank]
+                .iter()
+                .enumerate()
+                .map(|(i, &n)| (n, i))
+                .collect();
+
+            // Only nodes with at least one neighbour in the adjacent rank move; compute their
+            // median value but keep their slot index so we can write movers back into exactly
+            // the set of slots movers started in, leaving fixed (neighbourless) nodes untouched.
+            let mut movable: Vec<(usize, NodeIx, f64)> = Vec::new();
+            for (slot, &n) in order[r].iter().enumerate() {
+                let mut positions: Vec<usize> = self
+                    .graph
+                    .edges_directed(n, direction)
+                    .filter_map(|edge| {
+                        let other = if direction == Incoming {
+                            edge.source()
+                        } else {
+                            edge.target()
+                        };
+                        position_in_adjacent.get(&other).copied()
+                    })
+                    .collect();
+                if positions.is_empty() {
+                    continue;
+                }
+                positions.sort_unstable();
+                movable.push((slot, n, median_value(&positions)));
+            }
+
+            let mut slots: Vec<usize> = movable.iter().map(|(slot, ..)| *slot).collect();
+            slots.sort_unstable();
+            movable.sort_by(|a, b| a.2.partial_cmp(&b.2).unwrap());
+            for (slot, (_, node, _)) in slots.into_iter().zip(movable) {
+                order[r][slot] = node;
+            }
+        }
+    }
+
+    /// Repeatedly scan adjacent pairs within each rank, swapping whenever doing so doesn't
+    /// increase the crossings against the bordering ranks, until a full pass no longer reduces
+    /// them.
+    fn transpose(&self, order: &mut [Vec<NodeIx>]) {
+        loop {
+            let mut improved = false;
+            for r in 0..order.len() {
+                for i in 0..order[r].len().saturating_sub(1) {
+                    let before = self.crossings_around(order, r);
+                    order[r].swap(i, i + 1);
+                    let after = self.crossings_around(order, r);
+                    if after <= before {
+                        improved |= after < before;
+                    } else {
+                        order[r].swap(i, i + 1);
+                    }
+                }
+            }
+            if !improved {
+                break;
+            }
+        }
+    }
+
+    /// Crossings between rank `r` and whichever of its neighbouring ranks (`r-1`/`r+1`) exist.
+    fn crossings_around(&self, order: &[Vec<NodeIx>], r: usize) -> u64 {
+        let mut total = 0;
+        if r > 0 {
+            total += self.count_crossings(&order[r - 1], &order[r]);
+        }
+        if r + 1 < order.len() {
+            total += self.count_crossings(&order[r], &order[r + 1]);
+        }
+        total
+    }
+
+    fn total_crossings(&self, order: &[Vec<NodeIx>]) -> u64 {
+        (0..order.len().saturating_sub(1))
+            .map(|r| self.count_crossings(&order[r], &order[r + 1]))
+            .sum()
+    }
+
+    /// Count edge crossings between two adjacent ranks in O(E log E).
+    ///
+    /// Walking `upper` left to right and, for each node, recording the rank-`lower` position of
+    /// every outgoing edge (sorted, so edges sharing a tail don't spuriously "cross" each other)
+    /// produces a sequence whose inversions are exactly the crossings: two edges cross iff their
+    /// upper positions and lower positions disagree in order, which is precisely what an
+    /// inversion in this sequence captures.
+    fn count_crossings(&self, upper: &[NodeIx], lower: &[NodeIx]) -> u64 {
+        let lower_pos: HashMap<NodeIx, usize> =
+            lower.iter().enumerate().map(|(i, &n)| (n, i)).collect();
+
+        let mut sequence = Vec::new();
+        for &n in upper {
+            let mut targets: Vec<usize> = self
+                .graph
+                .edges_directed(n, Outgoing)
+                .filter_map(|edge| lower_pos.get(&edge.target()).copied())
+                .collect();
+            targets.sort_unstable();
+            sequence.extend(targets);
+        }
+        count_inversions(&mut sequence)
+    }
+}
+
+/// Count inversions in `sequence` with a recursive (top-down) merge sort: while merging two
+/// sorted halves, every time a right-half element is taken before the left half is exhausted,
+/// it is out of order with respect to all remaining left-half elements, so add their count.
+fn count_inversions(sequence: &mut [usize]) -> u64 {
+    let mut buffer = sequence.to_vec();
+    merge_count(sequence, &mut buffer)
+}
+
+fn merge_count(sequence: &mut [usize], buffer: &mut [usize]) -> u64 {
+    let len = sequence.len();
+    if len <= 1 {
+        return 0;
+    }
+    let mid = len / 2;
+    let mut inversions = merge_count(&mut sequence[..mid], &mut buffer[..mid]);
+    inversions += merge_count(&mut sequence[mid..], &mut buffer[mid..]);
+
+    buffer.copy_from_slice(sequence);
+    let (left, right) = buffer.split_at(mid);
+    let (mut i, mut j, mut k) = (0, 0, 0);
+    while i < left.len() && j < right.len() {
+        if left[i] <= right[j] {
+            sequence[k] = left[i];
+            i += 1;
+        } else {
+            sequence[k] = right[j];
+            j += 1;
+            inversions += (left.len() - i) as u64;
+        }
+        k += 1;
+    }
+    if i < left.len() {
+        sequence[k..].copy_from_slice(&left[i..]);
+    }
+    if j < right.len() {
+        sequence[k..].copy_from_slice(&right[j..]);
+    }
+    inversions
+}
+
+/// Weighted median of a sorted list of neighbour positions, per Gansner's `medianvalue`: the
+/// middle element for an odd count, and for an even count an interpolation between the two
+/// central elements weighted by the gaps to the list's extremes.
+fn median_value(positions: &[usize]) -> f64 {
+    let len = positions.len();
+    let m = len / 2;
+    if len % 2 == 1 {
+        return positions[m] as f64;
+    }
+    let left = positions[m - 1] as f64 - positions[0] as f64;
+    let right = positions[len - 1] as f64 - positions[m] as f64;
+    if left + right == 0.0 {
+        (positions[m - 1] as f64 + positions[m] as f64) / 2.0
+    } else {
+        (positions[m - 1] as f64 * right + positions[m] as f64 * left) / (left + right)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_inversions_sorted_is_zero() {
+        assert_eq!(count_inversions(&mut [0, 1, 2, 3]), 0);
+    }
+
+    #[test]
+    fn count_inversions_reversed_is_maximal() {
+        // every pair is out of order: n*(n-1)/2
+        assert_eq!(count_inversions(&mut [3, 2, 1, 0]), 6);
+    }
+
+    #[test]
+    fn count_inversions_counts_each_pair_once() {
+        // (2,1) and (2,0) cross the later elements; 0 and 1 stay in order with each other.
+        assert_eq!(count_inversions(&mut [2, 0, 1]), 2);
+    }
+
+    #[test]
+    fn median_value_odd_is_middle_element() {
+        assert_eq!(median_value(&[1, 5, 9]), 5.0);
+    }
+
+    #[test]
+    fn median_value_even_interpolates_by_gap() {
+        // symmetric gaps either side of the middle pair average them
+        assert_eq!(median_value(&[0, 2, 4, 6]), 3.0);
+    }
+
+    #[test]
+    fn median_value_even_equal_middle_elements() {
+        // zero gap on both sides (a run of duplicates) falls back to their plain average
+        assert_eq!(median_value(&[2, 2, 2, 2]), 2.0);
+    }
+}
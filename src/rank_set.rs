@@ -100,24 +100,24 @@ impl RankSets {
         })
     }
 
-    /// Get all nodes with given rank
-    fn rank(&self, idx: RankIdx) -> impl Iterator<Item = &Node> + '_ {
-        self.ranks.iter().filter_map(
-            move |(node, rank_idx)| {
-                if *rank_idx == idx {
-                    Some(node)
-                } else {
-                    None
-                }
-            },
-        )
-    }
-
     /// Get the rank for a particular node.
     pub fn node_rank(&self, node: Node) -> Option<RankIdx> {
         self.ranks.get(&node).copied()
     }
 
+    /// Every `set_rank`-hinted group of nodes that must end up on the same rank, excluding the
+    /// min/max groups (those are routed through edges in `make_acyclic` instead, rather than
+    /// collapsed to a single rank value here).
+    pub fn same_rank_groups(&self) -> impl Iterator<Item = Vec<Node>> + '_ {
+        let mut groups: HashMap<RankIdx, Vec<Node>> = HashMap::new();
+        for (&node, &rank) in &self.ranks {
+            if rank != Self::MIN_RANK && rank != Self::MAX_RANK {
+                groups.entry(rank).or_default().push(node);
+            }
+        }
+        groups.into_values()
+    }
+
     fn merge_ranks(&mut self, from: RankIdx, to: RankIdx) {
         for rank in self.ranks.values_mut() {
             if *rank == from {
@@ -0,0 +1,454 @@
+//! Network-simplex solver shared by rank assignment and (later) x-coordinate assignment.
+//!
+//! Both problems reduce to the same shape: find integer node labels minimizing
+//! Σ weight·(label(head) − label(tail)) subject to label(head) − label(tail) ≥ min_len for every
+//! edge. This module works on plain `0..node_count` indices so it has no dependency on the main
+//! `Gansner` graph, letting callers build whatever auxiliary graph their stage needs.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// A single constraint/objective edge for the simplex solver.
+pub(crate) struct SimplexEdge {
+    pub(crate) tail: usize,
+    pub(crate) head: usize,
+    pub(crate) min_len: i64,
+    pub(crate) weight: f64,
+}
+
+fn slack(edge: &SimplexEdge, labels: &[i64]) -> i64 {
+    labels[edge.head] - labels[edge.tail] - edge.min_len
+}
+
+fn incident_edges(node_count: usize, edges: &[SimplexEdge]) -> Vec<Vec<usize>> {
+    let mut adj = vec![Vec::new(); node_count];
+    for (i, edge) in edges.iter().enumerate() {
+        adj[edge.tail].push(i);
+        adj[edge.head].push(i);
+    }
+    adj
+}
+
+/// A feasible initial ranking: longest path from the sources, computed over a topological order.
+/// Every edge is guaranteed non-negative slack since `label[head] >= label[tail] + min_len`.
+fn init_labels(node_count: usize, edges: &[SimplexEdge]) -> Vec<i64> {
+    let mut remaining_indeg = vec![0usize; node_count];
+    let mut out_edges: Vec<Vec<usize>> = vec![Vec::new(); node_count];
+    for (i, edge) in edges.iter().enumerate() {
+        remaining_indeg[edge.head] += 1;
+        out_edges[edge.tail].push(i);
+    }
+
+    let mut labels = vec![0i64; node_count];
+    let mut queue: VecDeque<usize> = (0..node_count)
+        .filter(|&n| remaining_indeg[n] == 0)
+        .collect();
+    while let Some(n) = queue.pop_front() {
+        for &ei in &out_edges[n] {
+            let edge = &edges[ei];
+            labels[edge.head] = labels[edge.head].max(labels[n] + edge.min_len);
+            remaining_indeg[edge.head] -= 1;
+            if remaining_indeg[edge.head] == 0 {
+                queue.push_back(edge.head);
+            }
+        }
+    }
+    debug_assert!(
+        remaining_indeg.iter().all(|&d| d == 0),
+        "simplex input graph must be acyclic"
+    );
+    labels
+}
+
+/// Grow a spanning tight tree, one weakly-connected component at a time (nothing requires the
+/// caller's graph to be connected - an isolated node, or two unrelated subgraphs added to the
+/// same `Gansner`, are both ordinary inputs). The returned set is a spanning forest: one tight
+/// tree per component, with no edges between components since none exist in the input.
+fn feasible_tree(node_count: usize, edges: &[SimplexEdge], labels: &mut [i64]) -> HashSet<usize> {
+    let adj = incident_edges(node_count, edges);
+    let mut tree = HashSet::new();
+    let mut assigned = vec![false; node_count];
+    for start in 0..node_count {
+        if assigned[start] {
+            continue;
+        }
+        let (component_tree, component) = grow_tight_tree(&adj, edges, labels, start);
+        for n in component {
+            assigned[n] = true;
+        }
+        tree.extend(component_tree);
+    }
+    tree
+}
+
+/// Grow a tight tree spanning every node weakly reachable from `start`, shifting the tree
+/// towards the rest of its component when it gets stuck before spanning all of them. Returns the
+/// tree along with the full set of nodes in `start`'s component, so the caller can skip them when
+/// picking the next component's start node.
+fn grow_tight_tree(
+    adj: &[Vec<usize>],
+    edges: &[SimplexEdge],
+    labels: &mut [i64],
+    start: usize,
+) -> (HashSet<usize>, Vec<usize>) {
+    let component = reachable_from(adj, edges, start);
+    loop {
+        let mut in_tree = vec![false; adj.len()];
+        let mut tree = HashSet::new();
+        in_tree[start] = true;
+        let mut frontier = vec![start];
+        while let Some(n) = frontier.pop() {
+            for &ei in &adj[n] {
+                if tree.contains(&ei) {
+                    continue;
+                }
+                let edge = &edges[ei];
+                let other = if edge.tail == n { edge.head } else { edge.tail };
+                if in_tree[other] || slack(edge, labels) != 0 {
+                    continue;
+                }
+                tree.insert(ei);
+                in_tree[other] = true;
+                frontier.push(other);
+            }
+        }
+
+        if component.iter().all(|&n| in_tree[n]) {
+            return (tree, component);
+        }
+
+        // Stuck: find the minimum-slack edge leaving the tree and shift the whole tree
+        // component by that slack so the edge becomes tight.
+        let mut best: Option<(i64, usize)> = None;
+        for &n in &component {
+            if !in_tree[n] {
+                continue;
+            }
+            for &ei in &adj[n] {
+                let edge = &edges[ei];
+                let other = if edge.tail == n { edge.head } else { edge.tail };
+                if in_tree[other] {
+                    continue;
+                }
+                let s = slack(edge, labels);
+                if best.is_none_or(|(best_s, _)| s < best_s) {
+                    best = Some((s, ei));
+                }
+            }
+        }
+        let (min_slack, ei) = best.expect("a weakly-connected component must stay connected");
+        let edge = &edges[ei];
+        let delta = if in_tree[edge.tail] {
+            min_slack
+        } else {
+            -min_slack
+        };
+        for &n in &component {
+            if in_tree[n] {
+                labels[n] += delta;
+            }
+        }
+    }
+}
+
+/// Every node reachable from `start` by following edges in either direction (i.e. `start`'s
+/// weakly-connected component).
+fn reachable_from(adj: &[Vec<usize>], edges: &[SimplexEdge], start: usize) -> Vec<usize> {
+    let mut seen = vec![false; adj.len()];
+    seen[start] = true;
+    let mut component = vec![start];
+    let mut stack = vec![start];
+    while let Some(n) = stack.pop() {
+        for &ei in &adj[n] {
+            let edge = &edges[ei];
+            let other = if edge.tail == n { edge.head } else { edge.tail };
+            if !seen[other] {
+                seen[other] = true;
+                component.push(other);
+                stack.push(other);
+            }
+        }
+    }
+    component
+}
+
+fn tree_adjacency(node_count: usize, edges: &[SimplexEdge], tree: &HashSet<usize>) -> Vec<Vec<usize>> {
+    let mut adj = vec![Vec::new(); node_count];
+    for &ei in tree {
+        let edge = &edges[ei];
+        adj[edge.tail].push(ei);
+        adj[edge.head].push(ei);
+    }
+    adj
+}
+
+/// Nodes reachable from `start` within the tree, without crossing `excluded_edge`. Used to find
+/// the tail-side component obtained by deleting a tree edge.
+fn component_excluding(
+    edges: &[SimplexEdge],
+    tree_adj: &[Vec<usize>],
+    excluded_edge: usize,
+    start: usize,
+) -> Vec<bool> {
+    let mut in_component = vec![false; tree_adj.len()];
+    in_component[start] = true;
+    let mut stack = vec![start];
+    while let Some(n) = stack.pop() {
+        for &ei in &tree_adj[n] {
+            if ei == excluded_edge {
+                continue;
+            }
+            let edge = &edges[ei];
+            let other = if edge.tail == n { edge.head } else { edge.tail };
+            if !in_component[other] {
+                in_component[other] = true;
+                stack.push(other);
+            }
+        }
+    }
+    in_component
+}
+
+/// Cut value of every tree edge: the weight of all graph edges crossing from the tail-side
+/// component to the head-side component, minus the weight crossing the other way, once that
+/// edge is deleted from the tree.
+///
+/// This recomputes the two components from scratch for each tree edge (O(V + E) per edge) rather
+/// than Gansner's O(V) total postorder walk; it's simpler to get right and our graphs are small.
+fn cut_values(node_count: usize, edges: &[SimplexEdge], tree: &HashSet<usize>) -> HashMap<usize, f64> {
+    let tree_adj = tree_adjacency(node_count, edges, tree);
+    let mut values = HashMap::with_capacity(tree.len());
+    for &tree_edge in tree {
+        let tail_component =
+            component_excluding(edges, &tree_adj, tree_edge, edges[tree_edge].tail);
+        let mut cut = 0.0;
+        for edge in edges {
+            match (tail_component[edge.tail], tail_component[edge.head]) {
+                (true, false) => cut += edge.weight,
+                (false, true) => cut -= edge.weight,
+                _ => {}
+            }
+        }
+        values.insert(tree_edge, cut);
+    }
+    values
+}
+
+/// Repeatedly swap out tree edges with negative cut value for the minimum-slack edge that
+/// reconnects the resulting components, until every tree edge has a non-negative cut value.
+fn minimize_tree(
+    node_count: usize,
+    edges: &[SimplexEdge],
+    mut tree: HashSet<usize>,
+    labels: &mut [i64],
+) -> HashSet<usize> {
+    loop {
+        let cuts = cut_values(node_count, edges, &tree);
+        // Tie-break on edge index: `cuts` is a `HashMap`, so two edges with equal cut value would
+        // otherwise leave via whichever one its randomized iteration order happens to visit first,
+        // making the resulting ranks non-reproducible across runs of the same input.
+        let leave_edge = cuts
+            .iter()
+            .filter(|(_, &value)| value < 0.0)
+            .min_by(|a, b| a.1.partial_cmp(b.1).unwrap().then(a.0.cmp(b.0)))
+            .map(|(&ei, _)| ei);
+        let Some(leave_edge) = leave_edge else {
+            return tree;
+        };
+
+        let tree_adj = tree_adjacency(node_count, edges, &tree);
+        let tail_component =
+            component_excluding(edges, &tree_adj, leave_edge, edges[leave_edge].tail);
+
+        // The enter edge is a non-tree edge running from the head-side component back into the
+        // tail-side component (the opposite direction to the leave edge), with minimum slack.
+        let mut best: Option<(i64, usize)> = None;
+        for (ei, edge) in edges.iter().enumerate() {
+            if tree.contains(&ei) {
+                continue;
+            }
+            if !tail_component[edge.tail] && tail_component[edge.head] {
+                let s = slack(edge, labels);
+                if best.is_none_or(|(best_s, _)| s < best_s) {
+                    best = Some((s, ei));
+                }
+            }
+        }
+        let (delta, enter_edge) = best.expect("simplex input graph must stay connected");
+
+        tree.remove(&leave_edge);
+        tree.insert(enter_edge);
+        for (n, label) in labels.iter_mut().enumerate() {
+            if !tail_component[n] {
+                *label += delta;
+            }
+        }
+    }
+}
+
+/// Shift every label so the minimum is zero.
+fn normalize(labels: &mut [i64]) {
+    if let Some(&min) = labels.iter().min() {
+        for label in labels.iter_mut() {
+            *label -= min;
+        }
+    }
+}
+
+/// For nodes whose in-weight equals out-weight, moving them within their feasible range doesn't
+/// change the objective; nudge each one to whichever label in that range is least occupied, so
+/// nodes spread out instead of bunching onto a few labels.
+fn balance(node_count: usize, edges: &[SimplexEdge], labels: &mut [i64]) {
+    let mut in_weight = vec![0.0; node_count];
+    let mut out_weight = vec![0.0; node_count];
+    let mut in_edges: Vec<Vec<usize>> = vec![Vec::new(); node_count];
+    let mut out_edges: Vec<Vec<usize>> = vec![Vec::new(); node_count];
+    for (i, edge) in edges.iter().enumerate() {
+        out_weight[edge.tail] += edge.weight;
+        in_weight[edge.head] += edge.weight;
+        out_edges[edge.tail].push(i);
+        in_edges[edge.head].push(i);
+    }
+
+    let mut occupancy: HashMap<i64, usize> = HashMap::new();
+    for &label in labels.iter() {
+        *occupancy.entry(label).or_insert(0) += 1;
+    }
+
+    for n in 0..node_count {
+        if (in_weight[n] - out_weight[n]).abs() > f64::EPSILON {
+            continue;
+        }
+        let low = in_edges[n]
+            .iter()
+            .map(|&ei| labels[edges[ei].tail] + edges[ei].min_len)
+            .max();
+        let high = out_edges[n]
+            .iter()
+            .map(|&ei| labels[edges[ei].head] - edges[ei].min_len)
+            .min();
+        let (Some(low), Some(high)) = (low, high) else {
+            continue;
+        };
+        if low >= high {
+            continue;
+        }
+
+        let mut best_label = labels[n];
+        let mut best_count = occupancy.get(&best_label).copied().unwrap_or(0);
+        for candidate in low..=high {
+            let count = occupancy.get(&candidate).copied().unwrap_or(0);
+            if count < best_count {
+                best_count = count;
+                best_label = candidate;
+            }
+        }
+        if best_label != labels[n] {
+            *occupancy.get_mut(&labels[n]).unwrap() -= 1;
+            *occupancy.entry(best_label).or_insert(0) += 1;
+            labels[n] = best_label;
+        }
+    }
+}
+
+/// Solve the network-simplex problem, without the rank-specific balancing pass. Used directly by
+/// stages (like x-coordinate assignment) that do their own post-processing.
+pub(crate) fn solve(node_count: usize, edges: &[SimplexEdge]) -> Vec<i64> {
+    if node_count == 0 {
+        return Vec::new();
+    }
+    let mut labels = init_labels(node_count, edges);
+    let tree = feasible_tree(node_count, edges, &mut labels);
+    minimize_tree(node_count, edges, tree, &mut labels);
+    normalize(&mut labels);
+    labels
+}
+
+/// Solve the network-simplex problem and balance nodes with equal in/out weight across their
+/// feasible range. This is the ranking-stage entry point.
+pub(crate) fn rank(node_count: usize, edges: &[SimplexEdge]) -> Vec<i64> {
+    if node_count == 0 {
+        return Vec::new();
+    }
+    let mut labels = init_labels(node_count, edges);
+    let tree = feasible_tree(node_count, edges, &mut labels);
+    minimize_tree(node_count, edges, tree, &mut labels);
+    balance(node_count, edges, &mut labels);
+    normalize(&mut labels);
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edge(tail: usize, head: usize, min_len: i64, weight: f64) -> SimplexEdge {
+        SimplexEdge {
+            tail,
+            head,
+            min_len,
+            weight,
+        }
+    }
+
+    #[test]
+    fn rank_chain_increments_by_min_len() {
+        // a -> b -> c, unit length: the only feasible (and optimal) ranking is 0, 1, 2.
+        let edges = vec![edge(0, 1, 1, 1.), edge(1, 2, 1, 1.)];
+        assert_eq!(rank(3, &edges), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn rank_respects_min_len_greater_than_one() {
+        let edges = vec![edge(0, 1, 3, 1.)];
+        assert_eq!(rank(2, &edges), vec![0, 3]);
+    }
+
+    #[test]
+    fn rank_isolated_node_does_not_panic() {
+        // a -> b, plus an unconnected node c: used to panic in `feasible_tree`.
+        let edges = vec![edge(0, 1, 1, 1.)];
+        let ranks = rank(3, &edges);
+        assert_eq!(ranks[0], 0);
+        assert_eq!(ranks[1], 1);
+    }
+
+    #[test]
+    fn rank_two_disjoint_edges_does_not_panic() {
+        // a -> b and c -> d, two separate weakly-connected components.
+        let edges = vec![edge(0, 1, 1, 1.), edge(2, 3, 1, 1.)];
+        let ranks = rank(4, &edges);
+        assert_eq!(ranks[1] - ranks[0], 1);
+        assert_eq!(ranks[3] - ranks[2], 1);
+    }
+
+    #[test]
+    fn feasible_tree_spans_every_node_in_a_simple_dag() {
+        // a -> b -> d, a -> c -> d: a diamond, 4 nodes, 4 edges.
+        let edges = vec![
+            edge(0, 1, 1, 1.),
+            edge(0, 2, 1, 1.),
+            edge(1, 3, 1, 1.),
+            edge(2, 3, 1, 1.),
+        ];
+        let mut labels = init_labels(4, &edges);
+        let tree = feasible_tree(4, &edges, &mut labels);
+        // A tight spanning tree over 4 nodes has exactly 3 edges.
+        assert_eq!(tree.len(), 3);
+    }
+
+    #[test]
+    fn cut_values_negative_triggers_a_better_tree() {
+        // a -> b and a -> c -> d -> e, plus a heavy b -> e edge. The longest-path start puts b on
+        // rank 1, so b -> e spans two ranks; the tight tree edge a -> b then has cut value
+        // 1 - 10 = -9, and swapping it for b -> e lifts b to rank 2.
+        let edges = vec![
+            edge(0, 1, 1, 1.),
+            edge(0, 2, 1, 1.),
+            edge(2, 3, 1, 1.),
+            edge(3, 4, 1, 1.),
+            edge(1, 4, 1, 10.),
+        ];
+        let ranks = rank(5, &edges);
+        assert_eq!(ranks, vec![0, 2, 1, 2, 3]);
+    }
+}
@@ -0,0 +1,181 @@
+//! Edge routing: turns a chain of points (an edge's endpoints plus any virtual nodes a long edge
+//! was split across) into a drawable spline.
+//!
+//! Straight edges and two-segment chains are drawn as straight line(s); longer chains are smoothed
+//! into a single curve with a Catmull-Rom-to-Bezier conversion so the route doesn't kink at every
+//! virtual node.
+
+use kurbo::{BezPath, Point, Size, Vec2};
+
+/// Build the path for a self-loop edge (a node with an edge to itself): `route` can't handle this
+/// since its single waypoint would collapse to a bare `MoveTo`, so instead draw a small loop
+/// bulging out from the right of the node's bounding box and back.
+pub(crate) fn route_self_loop(center: Point, size: Size) -> BezPath {
+    let start = center + Vec2::new(size.width / 2.0, -size.height / 4.0);
+    let end = center + Vec2::new(size.width / 2.0, size.height / 4.0);
+    let bulge = (size.width / 2.0).max(size.height / 2.0).max(1.0);
+
+    let mut path = BezPath::new();
+    path.move_to(start);
+    path.curve_to(start + Vec2::new(bulge, 0.0), end + Vec2::new(bulge, 0.0), end);
+    path
+}
+
+/// Build the path for an edge given its full chain of waypoints (`from`'s position, one per
+/// intermediate virtual node, then `to`'s position), clipping the two end points to the boundary
+/// of `from_size`/`to_size` so the path starts/ends at the node's edge rather than its center.
+pub(crate) fn route(mut points: Vec<Point>, from_size: Size, to_size: Size) -> BezPath {
+    let last = points.len() - 1;
+    let mut path = BezPath::new();
+    if last == 0 {
+        path.move_to(points[0]);
+        return path;
+    }
+
+    points[0] = clip_to_box(points[0], from_size, points[1]);
+    points[last] = clip_to_box(points[last], to_size, points[last - 1]);
+
+    path.move_to(points[0]);
+    if points.len() <= 3 {
+        for &p in &points[1..] {
+            path.line_to(p);
+        }
+    } else {
+        catmull_rom_to_bezier(&points, &mut path);
+    }
+    path
+}
+
+/// Move `point` (the center of a node's bounding box, `size`) to the boundary of that box, along
+/// the line towards `towards`.
+fn clip_to_box(point: Point, size: Size, towards: Point) -> Point {
+    let half = Size::new(size.width / 2.0, size.height / 2.0);
+    if half.width == 0.0 && half.height == 0.0 {
+        return point;
+    }
+    let delta = towards - point;
+    if delta == kurbo::Vec2::ZERO {
+        return point;
+    }
+
+    let mut scale = f64::INFINITY;
+    if delta.x != 0.0 {
+        scale = scale.min(half.width / delta.x.abs());
+    }
+    if delta.y != 0.0 {
+        scale = scale.min(half.height / delta.y.abs());
+    }
+    point + delta * scale.min(1.0)
+}
+
+/// Convert a polyline into a single smooth cubic-Bezier spline via the standard Catmull-Rom
+/// tangent construction (each control point is offset from its point by a sixth of the chord
+/// between that point's neighbours),
+/// clamping the end segments to use the path's own endpoints in place of an out-of-range neighbour.
+fn catmull_rom_to_bezier(points: &[Point], path: &mut BezPath) {
+    let n = points.len();
+    for i in 0..n - 1 {
+        let p_prev = if i == 0 { points[0] } else { points[i - 1] };
+        let p0 = points[i];
+        let p1 = points[i + 1];
+        let p_next = if i + 2 < n { points[i + 2] } else { points[n - 1] };
+
+        let c1 = p0 + (p1 - p_prev) / 6.0;
+        let c2 = p1 - (p_next - p0) / 6.0;
+        path.curve_to(c1, c2, p1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kurbo::PathEl;
+
+    #[test]
+    fn route_self_loop_starts_and_ends_at_distinct_points() {
+        let path = route_self_loop(Point::new(5., 5.), Size::new(10., 10.));
+        let els: Vec<_> = path.elements().to_vec();
+        assert!(matches!(els[0], PathEl::MoveTo(_)));
+        assert!(matches!(els[1], PathEl::CurveTo(..)));
+        let PathEl::MoveTo(start) = els[0] else { unreachable!() };
+        let PathEl::CurveTo(.., end) = els[1] else { unreachable!() };
+        assert_ne!(start, end);
+    }
+
+    #[test]
+    fn clip_to_box_moves_to_boundary_towards_target() {
+        let clipped = clip_to_box(Point::new(0., 0.), Size::new(10., 4.), Point::new(10., 0.));
+        assert_eq!(clipped, Point::new(5., 0.));
+    }
+
+    #[test]
+    fn clip_to_box_zero_size_is_a_no_op() {
+        let p = Point::new(3., 7.);
+        assert_eq!(clip_to_box(p, Size::ZERO, Point::new(100., 100.)), p);
+    }
+
+    #[test]
+    fn clip_to_box_same_point_is_a_no_op() {
+        let p = Point::new(3., 7.);
+        assert_eq!(clip_to_box(p, Size::new(10., 10.), p), p);
+    }
+
+    #[test]
+    fn route_single_waypoint_is_a_bare_move_to() {
+        let path = route(vec![Point::new(1., 2.)], Size::ZERO, Size::ZERO);
+        let els: Vec<_> = path.elements().to_vec();
+        assert_eq!(els, vec![PathEl::MoveTo(Point::new(1., 2.))]);
+    }
+
+    #[test]
+    fn route_two_points_is_a_straight_line() {
+        let path = route(
+            vec![Point::new(0., 0.), Point::new(10., 0.)],
+            Size::ZERO,
+            Size::ZERO,
+        );
+        let els: Vec<_> = path.elements().to_vec();
+        assert_eq!(
+            els,
+            vec![
+                PathEl::MoveTo(Point::new(0., 0.)),
+                PathEl::LineTo(Point::new(10., 0.)),
+            ]
+        );
+    }
+
+    #[test]
+    fn route_clips_endpoints_to_node_boundaries() {
+        let path = route(
+            vec![Point::new(0., 0.), Point::new(10., 0.)],
+            Size::new(4., 4.),
+            Size::new(4., 4.),
+        );
+        let els: Vec<_> = path.elements().to_vec();
+        assert_eq!(
+            els,
+            vec![
+                PathEl::MoveTo(Point::new(2., 0.)),
+                PathEl::LineTo(Point::new(8., 0.)),
+            ]
+        );
+    }
+
+    #[test]
+    fn route_long_chain_is_a_single_smooth_curve() {
+        let path = route(
+            vec![
+                Point::new(0., 0.),
+                Point::new(5., 5.),
+                Point::new(10., 0.),
+                Point::new(15., 5.),
+            ],
+            Size::ZERO,
+            Size::ZERO,
+        );
+        let els: Vec<_> = path.elements().to_vec();
+        assert_eq!(els.len(), 4); // one MoveTo plus one CurveTo per segment
+        assert!(matches!(els[0], PathEl::MoveTo(_)));
+        assert!(els[1..].iter().all(|el| matches!(el, PathEl::CurveTo(..))));
+    }
+}